@@ -1,38 +1,44 @@
-use std::sync::LockResult;
-use std::sync::{Arc, Mutex, MutexGuard};
-use std::thread::{self, JoinHandle};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::{TryLockError, TryLockResult};
+use std::thread::{self, JoinHandle, Thread, ThreadId};
+use std::time::Duration;
 
 ///
 /// Deadlock-free Mutex locks
 ///
 /// DFMutex is a library that provides a ***guaranteed deadlock-free*** Mutex
 /// implementation for the Rust language. Based on the research paper Higher-Order
-/// Leak and Deadlock Free Locks by Jules Jacobs and Stephanie Balzer. 
-/// 
+/// Leak and Deadlock Free Locks by Jules Jacobs and Stephanie Balzer.
+///
 /// Example
 /// ```rust
 /// use dfmutex::{DFMutex, spawn};
-/// 
+///
 /// fn main() {
 ///     // Create a Mutex with any owned value
 ///     let m = DFMutex::new(String::from("Lorem Ipsum"));
-/// 
+///
 ///     // Create a closure to pass in the thread.
 ///     // The type of the created Mutex above should be same as the
 ///     // argument to the closure.
 ///     let closure = |mut dfm: DFMutex<String>| {
 ///         let data = dfm.lock().unwrap();
-///         
+///
 ///         // Use the data
 ///         println!("{}", data);
 ///     };
-/// 
+///
 ///     // Spawn 8 threads and store their handles
 ///     let mut handles = Vec::new();
 ///     for _ in 0..8 {
-///         handles.push(spawn(&m, closure));    
+///         handles.push(spawn(&m, closure));
 ///     }
-/// 
+///
 ///     // Join all the threads
 ///     for handle in handles.into_iter() {
 ///         handle.join().unwrap();
@@ -40,27 +46,674 @@ use std::thread::{self, JoinHandle};
 /// }
 /// ```
 
+/// The strategy a [`DFMutex`] uses to guarantee it never deadlocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Track a process-global wait-for graph and refuse an acquisition that
+    /// would close a cycle, returning a [`DeadlockError`] instead.
+    CycleDetection,
+    /// Require locks to always be acquired in strictly increasing id order.
+    /// Acquiring a lock whose id is not greater than one already held by the
+    /// current thread is rejected with a [`DeadlockError`].
+    Ordered,
+}
+
+/// Error returned instead of blocking forever when an acquisition would
+/// deadlock (or, under [`Policy::Ordered`], violate the required ordering).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadlockError {
+    /// The chain of lock ids that would form a cycle if the acquisition were
+    /// allowed to proceed. Under [`Policy::Ordered`] it is instead the locks
+    /// the current thread already holds, followed by the requested lock.
+    pub cycle: Vec<u64>,
+}
+
+impl fmt::Display for DeadlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "acquiring this lock would deadlock, cycle: {:?}", self.cycle)
+    }
+}
+
+impl std::error::Error for DeadlockError {}
+
+/// Result type returned by deadlock-aware lock operations.
+pub type Result<T> = std::result::Result<T, DeadlockError>;
+
+/// FIFO queue of threads parked waiting on a [`DFMutex`], shared between the
+/// mutex and the guards it hands out.
+type WaiterQueue = Arc<Mutex<VecDeque<Thread>>>;
+
+/// Parks the current thread on `queue` until it reaches the front and can
+/// acquire `mutex`, granting it in arrival order. Shared by [`DFMutex::lock`]'s
+/// contended path and [`DFCondvar::wait`]'s reacquire, so a condvar-notified
+/// thread joins the same line as everyone else instead of cutting in front of
+/// a thread already parked here.
+fn acquire_fifo<'a, T>(
+    mutex: &'a Mutex<T>,
+    queue: &WaiterQueue,
+    waiting: &Arc<AtomicUsize>,
+) -> MutexGuard<'a, T> {
+    waiting.fetch_add(1, Ordering::SeqCst);
+    queue.lock().unwrap().push_back(thread::current());
+
+    let guard = loop {
+        // Not every release of `mutex` unparks the front of the queue (the
+        // unlock inside `Condvar::wait` does not, and a release that lands
+        // before this thread was queued finds nobody to wake), so a parked
+        // waiter also re-checks after a short timeout.
+        thread::park_timeout(Duration::from_millis(1));
+
+        let at_front = {
+            let q = queue.lock().unwrap();
+            matches!(q.front(), Some(t) if t.id() == thread::current().id())
+        };
+
+        if at_front {
+            if let Ok(guard) = mutex.try_lock() {
+                queue.lock().unwrap().pop_front();
+                break guard;
+            }
+        }
+    };
+
+    waiting.fetch_sub(1, Ordering::SeqCst);
+    guard
+}
+
+/// Error returned by [`DFMutex::into_inner`] and [`DFMutex::get_mut`] when
+/// other `DFMutex` handles to the same lock are still alive, so unique
+/// access to the protected value cannot be proven.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StillSharedError;
+
+impl fmt::Display for StillSharedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "other DFMutex handles to this lock are still alive")
+    }
+}
+
+impl std::error::Error for StillSharedError {}
+
+static NEXT_LOCK_ID: AtomicU64 = AtomicU64::new(0);
+
+fn next_lock_id() -> u64 {
+    NEXT_LOCK_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The process-global wait-for graph: which threads currently hold each
+/// lock (a [`DFRwLock`] read lock may be held by more than one thread at
+/// once), and which lock (if any) each thread is currently blocked on. Both
+/// `DFMutex` and `DFRwLock` share this graph so a cycle spanning the two
+/// kinds of lock is still detected.
+#[derive(Debug, Default)]
+struct WaitForGraph {
+    holders: HashMap<u64, HashSet<ThreadId>>,
+    waits_for: HashMap<ThreadId, u64>,
+}
+
+fn graph() -> &'static Mutex<WaitForGraph> {
+    static GRAPH: OnceLock<Mutex<WaitForGraph>> = OnceLock::new();
+    GRAPH.get_or_init(|| Mutex::new(WaitForGraph::default()))
+}
+
+thread_local! {
+    /// Locks currently held by this thread. Used to check [`Policy::Ordered`].
+    static HELD_LOCKS: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Depth-first search over lock nodes, following `lock_id`'s holders and
+/// each holder's own wait edge, looking for a holder thread equal to
+/// `start`. Returns the chain of lock ids that would form the cycle.
+fn find_cycle(graph: &WaitForGraph, start: ThreadId, lock_id: u64) -> Option<Vec<u64>> {
+    let mut visited = HashSet::new();
+    let mut path = Vec::new();
+    find_cycle_from(graph, start, lock_id, &mut visited, &mut path)
+}
+
+fn find_cycle_from(
+    graph: &WaitForGraph,
+    start: ThreadId,
+    lock_id: u64,
+    visited: &mut HashSet<u64>,
+    path: &mut Vec<u64>,
+) -> Option<Vec<u64>> {
+    if !visited.insert(lock_id) {
+        return None;
+    }
+    path.push(lock_id);
+
+    if let Some(holders) = graph.holders.get(&lock_id) {
+        for &holder in holders {
+            if holder == start {
+                return Some(path.clone());
+            }
+
+            if let Some(&next_lock) = graph.waits_for.get(&holder) {
+                if let Some(cycle) = find_cycle_from(graph, start, next_lock, visited, path) {
+                    return Some(cycle);
+                }
+            }
+        }
+    }
+
+    path.pop();
+    None
+}
+
+/// Registers that the current thread is about to block waiting on
+/// `lock_id`, and rejects the acquisition if doing so would close a cycle.
+fn register_wait_edge(lock_id: u64) -> Result<()> {
+    let tid = thread::current().id();
+    let mut g = graph().lock().unwrap();
+
+    g.waits_for.insert(tid, lock_id);
+    if let Some(cycle) = find_cycle(&g, tid, lock_id) {
+        g.waits_for.remove(&tid);
+        return Err(DeadlockError { cycle });
+    }
+
+    Ok(())
+}
+
+/// Clears the current thread's wait edge, whether or not it ended up
+/// blocking, once the acquisition attempt is resolved.
+fn clear_wait_edge() {
+    let tid = thread::current().id();
+    graph().lock().unwrap().waits_for.remove(&tid);
+}
+
+/// Records that the current thread now holds `lock_id`.
+fn record_acquired(lock_id: u64) {
+    let tid = thread::current().id();
+    graph()
+        .lock()
+        .unwrap()
+        .holders
+        .entry(lock_id)
+        .or_default()
+        .insert(tid);
+    HELD_LOCKS.with(|held| held.borrow_mut().push(lock_id));
+}
+
+/// Records that the current thread no longer holds `lock_id`.
+fn record_released(lock_id: u64) {
+    let tid = thread::current().id();
+
+    let mut g = graph().lock().unwrap();
+    if let Some(holders) = g.holders.get_mut(&lock_id) {
+        holders.remove(&tid);
+        if holders.is_empty() {
+            g.holders.remove(&lock_id);
+        }
+    }
+    drop(g);
+
+    HELD_LOCKS.with(|held| held.borrow_mut().retain(|&h| h != lock_id));
+}
+
 /// A deadlock-free mutual exclusion primitive useful for protecting shared data
 #[derive(Debug)]
 pub struct DFMutex<T> {
+    id: u64,
+    policy: Policy,
     internal: Arc<Mutex<T>>,
+    /// FIFO queue of threads parked waiting for this lock, so a contended
+    /// acquire is granted in arrival order instead of whatever order
+    /// `std::sync::Mutex` happens to wake blocked lockers in.
+    queue: WaiterQueue,
+    waiting: Arc<AtomicUsize>,
 }
 
 impl<T> DFMutex<T> {
-    /// Creates a new mutex in an unlocked state ready for use.
+    /// Creates a new mutex in an unlocked state ready for use, guarded by
+    /// [`Policy::CycleDetection`].
     pub fn new(data: T) -> Self {
+        Self::with_policy(data, Policy::CycleDetection)
+    }
+
+    /// Creates a new mutex in an unlocked state, guarded by `policy`.
+    pub fn with_policy(data: T, policy: Policy) -> Self {
         DFMutex {
+            id: next_lock_id(),
+            policy,
             internal: Arc::new(Mutex::new(data)),
+            queue: Arc::new(Mutex::new(VecDeque::new())),
+            waiting: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// The unique id assigned to this lock at construction.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The number of threads currently parked waiting to acquire this lock.
+    pub fn waiters(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+
+    /// Acquires a mutex, blocking the current thread until it is able to do
+    /// so, unless doing so would deadlock, in which case a [`DeadlockError`]
+    /// is returned instead of blocking.
+    ///
+    /// A contended acquire parks the current thread on an explicit FIFO
+    /// waiter queue rather than blocking inside `std::sync::Mutex`, so
+    /// waiters are granted the lock in arrival order.
+    pub fn lock(&mut self) -> Result<DFMutexGuard<'_, T>> {
+        // Ordering is a static constraint on this thread's held locks, so it
+        // is checked regardless of whether `self` happens to be contended.
+        if self.policy == Policy::Ordered {
+            self.check_order()?;
+        }
+
+        // Fast path: nobody is already queued up, so jump straight in.
+        if self.waiting.load(Ordering::SeqCst) == 0 {
+            if let Ok(guard) = self.internal.try_lock() {
+                record_acquired(self.id);
+                return Ok(DFMutexGuard::new(self.id, guard, &self.internal, &self.queue, &self.waiting));
+            }
+        }
+
+        if self.policy == Policy::CycleDetection {
+            register_wait_edge(self.id)?;
+        }
+
+        let guard = acquire_fifo(&self.internal, &self.queue, &self.waiting);
+
+        if self.policy == Policy::CycleDetection {
+            clear_wait_edge();
+        }
+        record_acquired(self.id);
+
+        Ok(DFMutexGuard::new(self.id, guard, &self.internal, &self.queue, &self.waiting))
+    }
+
+    /// Attempts to acquire the lock without blocking.
+    ///
+    /// Unlike [`DFMutex::lock`], a `try_lock` never joins the FIFO waiter
+    /// queue or registers a wait-for edge and so cannot participate in a
+    /// cycle: if the lock is currently held, [`TryLockError::WouldBlock`] is
+    /// returned immediately instead. If any thread is already queued up
+    /// waiting its turn, `try_lock` also backs off with `WouldBlock` rather
+    /// than cutting in front of it, so a caller spinning on `try_lock`
+    /// cannot starve a thread parked in [`DFMutex::lock`].
+    ///
+    /// Under [`Policy::Ordered`], this enforces the same lock-ordering
+    /// constraint as [`DFMutex::lock`]: if this thread already holds a lock
+    /// with a higher id, `try_lock` backs off with
+    /// [`TryLockError::WouldBlock`] rather than taking it out of order.
+    /// `TryLockError` has no variant for carrying the offending
+    /// [`DeadlockError`], so unlike `lock`, the violation is reported as a
+    /// declined acquire rather than a distinguishable error.
+    pub fn try_lock(&mut self) -> TryLockResult<DFMutexGuard<'_, T>> {
+        if self.waiting.load(Ordering::SeqCst) != 0 {
+            return Err(TryLockError::WouldBlock);
+        }
+
+        if self.policy == Policy::Ordered && self.check_order().is_err() {
+            return Err(TryLockError::WouldBlock);
+        }
+
+        let guard = match self.internal.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::WouldBlock) => return Err(TryLockError::WouldBlock),
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+        };
+
+        record_acquired(self.id);
+        Ok(DFMutexGuard::new(self.id, guard, &self.internal, &self.queue, &self.waiting))
+    }
+
+    /// Consumes this handle and returns the protected value, without
+    /// locking, once it is the last surviving `DFMutex` handle.
+    ///
+    /// Fails with [`StillSharedError`] if other handles (for instance ones
+    /// still held by a [`spawn`]ed thread that has not been joined yet) are
+    /// alive, since the value cannot be uniquely reclaimed in that case.
+    pub fn into_inner(self) -> std::result::Result<T, StillSharedError> {
+        match Arc::try_unwrap(self.internal) {
+            Ok(mutex) => Ok(mutex.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner())),
+            Err(_) => Err(StillSharedError),
+        }
+    }
+
+    /// Returns unsynchronized mutable access to the protected value, which
+    /// is only granted once this is the last surviving `DFMutex` handle,
+    /// checked at run time since `&mut self` alone does not rule out other
+    /// clones.
+    ///
+    /// Fails with [`StillSharedError`] if other handles are alive.
+    pub fn get_mut(&mut self) -> std::result::Result<&mut T, StillSharedError> {
+        match Arc::get_mut(&mut self.internal) {
+            Some(mutex) => Ok(mutex.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner())),
+            None => Err(StillSharedError),
+        }
+    }
+
+    fn check_order(&self) -> Result<()> {
+        let id = self.id;
+        HELD_LOCKS.with(|held| {
+            let held = held.borrow();
+            if held.iter().any(|&h| h >= id) {
+                let mut cycle = held.clone();
+                cycle.push(id);
+                return Err(DeadlockError { cycle });
+            }
+            Ok(())
+        })
+    }
+
+    fn clone(&self) -> Self {
+        DFMutex {
+            id: self.id,
+            policy: self.policy,
+            internal: Arc::clone(&self.internal),
+            queue: Arc::clone(&self.queue),
+            waiting: Arc::clone(&self.waiting),
+        }
+    }
+}
+
+/// RAII guard returned by [`DFMutex::lock`]. Dropping it releases the
+/// underlying lock and clears this thread's bookkeeping in the wait-for
+/// graph.
+///
+/// `guard` is `None` only in the brief window where [`DFCondvar::wait`] has
+/// taken ownership of the underlying [`MutexGuard`] to hand to
+/// [`Condvar::wait`]; a guard in that state is never observed outside this
+/// module.
+#[derive(Debug)]
+pub struct DFMutexGuard<'a, T> {
+    id: u64,
+    guard: Option<MutexGuard<'a, T>>,
+    mutex: &'a Mutex<T>,
+    queue: WaiterQueue,
+    waiting: Arc<AtomicUsize>,
+}
+
+impl<'a, T> DFMutexGuard<'a, T> {
+    fn new(
+        id: u64,
+        guard: MutexGuard<'a, T>,
+        mutex: &'a Mutex<T>,
+        queue: &WaiterQueue,
+        waiting: &Arc<AtomicUsize>,
+    ) -> Self {
+        DFMutexGuard {
+            id,
+            guard: Some(guard),
+            mutex,
+            queue: Arc::clone(queue),
+            waiting: Arc::clone(waiting),
+        }
+    }
+
+    /// Releases this thread's held-lock bookkeeping and hands back the raw
+    /// `MutexGuard` so [`Condvar::wait`] can perform the actual atomic
+    /// unlock-and-park. A condvar wait never registers a wait-for edge, so
+    /// while the thread is parked it holds nothing as far as the cycle
+    /// detector is concerned.
+    fn release_for_wait(mut self) -> (u64, MutexGuard<'a, T>, &'a Mutex<T>, WaiterQueue, Arc<AtomicUsize>) {
+        let id = self.id;
+        record_released(id);
+        (
+            id,
+            self.guard.take().expect("guard already taken"),
+            self.mutex,
+            Arc::clone(&self.queue),
+            Arc::clone(&self.waiting),
+        )
+    }
+
+    /// Rebuilds a guard after [`Condvar::wait`] has reacquired the raw lock,
+    /// restoring this thread's held-lock bookkeeping.
+    fn reacquire_after_wait(
+        id: u64,
+        guard: MutexGuard<'a, T>,
+        mutex: &'a Mutex<T>,
+        queue: WaiterQueue,
+        waiting: Arc<AtomicUsize>,
+    ) -> DFMutexGuard<'a, T> {
+        record_acquired(id);
+        DFMutexGuard { id, guard: Some(guard), mutex, queue, waiting }
+    }
+}
+
+impl<T> Deref for DFMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard.as_deref().expect("guard already taken")
+    }
+}
+
+impl<T> DerefMut for DFMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_deref_mut().expect("guard already taken")
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for DFMutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for DFMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // `guard` is already `None` here when this drop is the tail end of a
+        // [`DFCondvar::wait`] hand-off: `release_for_wait` took the raw
+        // `MutexGuard` to give to `Condvar::wait`, which has not unlocked it
+        // yet at this point. Only a drop that actually empties `guard` here
+        // corresponds to a real unlock, so only that case should wake the
+        // next waiter — otherwise it wakes up to find the lock still held,
+        // fails to acquire it, and re-parks, burning its one unpark token.
+        let Some(guard) = self.guard.take() else {
+            return;
+        };
+
+        record_released(self.id);
+        drop(guard);
+
+        if let Some(next) = self.queue.lock().unwrap().front() {
+            next.unpark();
+        }
+    }
+}
+
+/// A condition variable paired with a [`DFMutex`] via [`DFCondvar::wait`],
+/// integrated with the wait-for graph: a thread parked here holds no locks,
+/// so it never contributes a wait-for edge and cannot be part of a reported
+/// cycle. Cloning a `DFCondvar` shares the same underlying condition
+/// variable, which is safe regardless of how many handles exist since it
+/// carries no lock id of its own.
+#[derive(Debug, Clone, Default)]
+pub struct DFCondvar {
+    internal: Arc<Condvar>,
+}
+
+impl DFCondvar {
+    /// Creates a new condition variable.
+    pub fn new() -> Self {
+        DFCondvar { internal: Arc::new(Condvar::new()) }
+    }
+
+    /// Atomically releases `guard` and blocks the current thread until
+    /// notified via [`DFCondvar::notify_one`] or [`DFCondvar::notify_all`],
+    /// then reacquires the lock before returning the guard.
+    ///
+    /// Unlike [`DFMutex::lock`], this never registers a wait-for edge: a
+    /// thread parked on a condvar holds nothing, so it cannot be part of a
+    /// cycle.
+    ///
+    /// `Condvar::wait` reacquires the raw lock itself, bypassing the FIFO
+    /// waiter queue. If anyone is already queued up for this lock when that
+    /// happens, this thread gives it back up and joins the same queue rather
+    /// than cutting in front of them.
+    pub fn wait<'a, T>(&self, guard: DFMutexGuard<'a, T>) -> DFMutexGuard<'a, T> {
+        let (id, guard, mutex, queue, waiting) = guard.release_for_wait();
+
+        let mut guard = self
+            .internal
+            .wait(guard)
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if waiting.load(Ordering::SeqCst) != 0 {
+            drop(guard);
+            guard = acquire_fifo(mutex, &queue, &waiting);
         }
+
+        DFMutexGuard::reacquire_after_wait(id, guard, mutex, queue, waiting)
+    }
+
+    /// Wakes up one thread blocked on this condvar's [`DFCondvar::wait`].
+    pub fn notify_one(&self) {
+        self.internal.notify_one();
     }
 
-    /// Acquires a mutex, blocking the current thread until it is able to do so.
-    pub fn lock(&mut self) -> LockResult<MutexGuard<'_, T>> {
-        self.internal.lock()
+    /// Wakes up all threads blocked on this condvar's [`DFCondvar::wait`].
+    pub fn notify_all(&self) {
+        self.internal.notify_all();
+    }
+}
+
+/// A deadlock-free reader-writer lock, a sibling of [`DFMutex`] that allows
+/// any number of concurrent readers or a single exclusive writer. It
+/// participates in the same global wait-for graph as `DFMutex`, so a cycle
+/// spanning a mutex and an rwlock is still detected.
+#[derive(Debug)]
+pub struct DFRwLock<T> {
+    id: u64,
+    internal: Arc<RwLock<T>>,
+}
+
+impl<T> DFRwLock<T> {
+    /// Creates a new reader-writer lock in an unlocked state ready for use.
+    pub fn new(data: T) -> Self {
+        DFRwLock {
+            id: next_lock_id(),
+            internal: Arc::new(RwLock::new(data)),
+        }
+    }
+
+    /// The unique id assigned to this lock at construction.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Locks this rwlock with shared read access, blocking the current
+    /// thread until it is able to do so, unless doing so would deadlock, in
+    /// which case a [`DeadlockError`] is returned instead of blocking.
+    pub fn read(&mut self) -> Result<DFRwLockReadGuard<'_, T>> {
+        if let Ok(guard) = self.internal.try_read() {
+            record_acquired(self.id);
+            return Ok(DFRwLockReadGuard { id: self.id, guard });
+        }
+
+        register_wait_edge(self.id)?;
+
+        let guard = self
+            .internal
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        clear_wait_edge();
+        record_acquired(self.id);
+
+        Ok(DFRwLockReadGuard { id: self.id, guard })
+    }
+
+    /// Locks this rwlock with exclusive write access, blocking the current
+    /// thread until it is able to do so, unless doing so would deadlock, in
+    /// which case a [`DeadlockError`] is returned instead of blocking.
+    pub fn write(&mut self) -> Result<DFRwLockWriteGuard<'_, T>> {
+        if let Ok(guard) = self.internal.try_write() {
+            record_acquired(self.id);
+            return Ok(DFRwLockWriteGuard { id: self.id, guard });
+        }
+
+        register_wait_edge(self.id)?;
+
+        let guard = self
+            .internal
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        clear_wait_edge();
+        record_acquired(self.id);
+
+        Ok(DFRwLockWriteGuard { id: self.id, guard })
     }
 
     fn clone(&self) -> Self {
-        DFMutex { internal: Arc::clone(&self.internal) }
+        DFRwLock {
+            id: self.id,
+            internal: Arc::clone(&self.internal),
+        }
+    }
+}
+
+/// RAII guard returned by [`DFRwLock::read`]. Dropping it releases this
+/// thread's share of the read lock and clears its bookkeeping in the
+/// wait-for graph.
+#[derive(Debug)]
+pub struct DFRwLockReadGuard<'a, T> {
+    id: u64,
+    guard: RwLockReadGuard<'a, T>,
+}
+
+impl<T> Deref for DFRwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for DFRwLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.guard, f)
+    }
+}
+
+impl<T> Drop for DFRwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        record_released(self.id);
+    }
+}
+
+/// RAII guard returned by [`DFRwLock::write`]. Dropping it releases the
+/// exclusive write lock and clears this thread's bookkeeping in the
+/// wait-for graph.
+#[derive(Debug)]
+pub struct DFRwLockWriteGuard<'a, T> {
+    id: u64,
+    guard: RwLockWriteGuard<'a, T>,
+}
+
+impl<T> Deref for DFRwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for DFRwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for DFRwLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.guard, f)
+    }
+}
+
+impl<T> Drop for DFRwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        record_released(self.id);
     }
 }
 
@@ -76,6 +729,19 @@ where
     thread::spawn(move || f(codfm))
 }
 
+/// Spawns a new thread bound to a [`DFRwLock`] clone, returning a
+/// [`JoinHandle`] for it.
+pub fn spawn_rw<D, T, F>(odfrw: &DFRwLock<D>, f: F) -> JoinHandle<T>
+where
+    F: FnOnce(DFRwLock<D>) -> T + Send + 'static,
+    D: Send + Sync + 'static,
+    T: Send + 'static,
+{
+    let codfrw = odfrw.clone();
+
+    thread::spawn(move || f(codfrw))
+}
+
 #[allow(dead_code)]
 mod test_commons {
     pub const TEST_ITERATIONS: std::ops::Range<i32> = 0..10;
@@ -92,7 +758,7 @@ mod test_commons {
 
     pub fn compute_intensive_task() -> u64 {
         fibonacci(TASK_BASE)
-    } 
+    }
 }
 
 #[cfg(test)]
@@ -119,7 +785,7 @@ mod single_lock {
         let mut handles = Vec::new();
 
         for _ in THREADS_RANGE {
-            handles.push(spawn(&m, closure));    
+            handles.push(spawn(&m, closure));
         }
 
         for handle in handles.into_iter() {
@@ -165,7 +831,7 @@ mod single_lock {
         let mut handles = Vec::new();
 
         for _ in THREADS_RANGE {
-            handles.push(spawn(&m, closure));    
+            handles.push(spawn(&m, closure));
         }
 
         for handle in handles.into_iter() {
@@ -241,7 +907,7 @@ mod lock_pair_straight_order {
             let mut handles = Vec::new();
 
             for _ in THREADS_RANGE {
-                handles.push(spawn(&m, closure));    
+                handles.push(spawn(&m, closure));
             }
 
             for handle in handles.into_iter() {
@@ -271,7 +937,7 @@ mod lock_pair_straight_order {
             let mut handles = Vec::new();
 
             for _ in THREADS_RANGE {
-                handles.push(spawn(&m, closure));    
+                handles.push(spawn(&m, closure));
             }
 
             for handle in handles.into_iter() {
@@ -330,7 +996,7 @@ mod lock_pair_swapped_order {
 
             for _ in THREADS_RANGE {
                 if flag {
-                    handles.push(spawn(&m, closure_a));    
+                    handles.push(spawn(&m, closure_a));
                 } else {
                     handles.push(spawn(&m, closure_b));
                 }
@@ -381,7 +1047,7 @@ mod lock_pair_swapped_order {
 
             for _ in THREADS_RANGE {
                 if flag {
-                    handles.push(spawn(&m, closure_a));    
+                    handles.push(spawn(&m, closure_a));
                 } else {
                     handles.push(spawn(&m, closure_b));
                 }
@@ -430,7 +1096,7 @@ mod lock_pair_swapped_order {
 
             for _ in THREADS_RANGE {
                 if flag {
-                    handles.push(spawn(&m, closure_a));    
+                    handles.push(spawn(&m, closure_a));
                 } else {
                     handles.push(spawn(&m, closure_b));
                 }
@@ -444,6 +1110,119 @@ mod lock_pair_swapped_order {
     }
 }
 
+#[cfg(test)]
+mod rwlock_pair_swapped_order {
+    use std::ops::DerefMut;
+    use rand::Rng;
+    use rand::thread_rng;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::DFMutex;
+    use super::DFRwLock;
+    use super::spawn;
+    use super::test_commons::*;
+
+    #[test]
+    pub fn constant_time() {
+        for _ in TEST_ITERATIONS {
+            let m1 = DFRwLock::new(String::from("1"));
+            let m2 = DFRwLock::new(String::from("2"));
+            let m = DFMutex::new((m1, m2));
+
+            let closure_a = |mut dfm: DFMutex<(DFRwLock<String>, DFRwLock<String>)>| {
+                let mut guard = dfm.lock().unwrap();
+                let (m1, m2) = guard.deref_mut();
+
+                let m1d = m1.read().unwrap();
+                let m2d = m2.write().unwrap();
+
+                thread::sleep(Duration::new(1, 0));
+
+                println!("{} {}", m1d, m2d);
+            };
+
+            let closure_b = |mut dfm: DFMutex<(DFRwLock<String>, DFRwLock<String>)>| {
+                let mut guard = dfm.lock().unwrap();
+                let (m1, m2) = guard.deref_mut();
+
+                let m2d = m2.read().unwrap();
+                let m1d = m1.write().unwrap();
+
+                thread::sleep(Duration::new(1, 0));
+
+                println!("{} {}", m2d, m1d);
+            };
+
+            let mut flag = true;
+            let mut handles = Vec::new();
+
+            for _ in THREADS_RANGE {
+                if flag {
+                    handles.push(spawn(&m, closure_a));
+                } else {
+                    handles.push(spawn(&m, closure_b));
+                }
+                flag = !flag;
+            }
+
+            for handle in handles.into_iter() {
+                handle.join().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    pub fn random_time() {
+        for _ in TEST_ITERATIONS {
+            let m1 = DFRwLock::new(String::from("1"));
+            let m2 = DFRwLock::new(String::from("2"));
+            let m = DFMutex::new((m1, m2));
+
+            let closure_a = |mut dfm: DFMutex<(DFRwLock<String>, DFRwLock<String>)>| {
+                let mut rng = thread_rng();
+                let mut guard = dfm.lock().unwrap();
+                let (m1, m2) = guard.deref_mut();
+
+                let m1d = m1.read().unwrap();
+                let m2d = m2.write().unwrap();
+
+                thread::sleep(Duration::new(rng.gen_range(1..3), 0));
+
+                println!("{} {}", m1d, m2d);
+            };
+
+            let closure_b = |mut dfm: DFMutex<(DFRwLock<String>, DFRwLock<String>)>| {
+                let mut rng = thread_rng();
+                let mut guard = dfm.lock().unwrap();
+                let (m1, m2) = guard.deref_mut();
+
+                let m2d = m2.read().unwrap();
+                let m1d = m1.write().unwrap();
+
+                thread::sleep(Duration::new(rng.gen_range(1..3), 0));
+
+                println!("{} {}", m2d, m1d);
+            };
+
+            let mut flag = true;
+            let mut handles = Vec::new();
+
+            for _ in THREADS_RANGE {
+                if flag {
+                    handles.push(spawn(&m, closure_a));
+                } else {
+                    handles.push(spawn(&m, closure_b));
+                }
+                flag = !flag;
+            }
+
+            for handle in handles.into_iter() {
+                handle.join().unwrap();
+            }
+        }
+    }
+}
 
 #[cfg(test)]
 mod dining_philisophers {
@@ -470,11 +1249,24 @@ mod dining_philisophers {
             thread::sleep(Duration::new(0, 100000));
         }
 
+        // Forks are always reached for left-then-right, the classic setup
+        // that lets every philosopher form a ring and circularly wait on
+        // their neighbour. Nothing here orders the locks for the caller:
+        // it's the wait-for graph's cycle detection that has to break the
+        // circular wait instead, by rejecting one philosopher's acquire
+        // with a `DeadlockError` so they skip this round rather than hang.
         pub fn eat(&mut self) {
-            let left_fork = self.left.lock().unwrap();
-            println!("{} Acquired L -> {}", self.id, left_fork);
-            let right_fork = self.right.lock().unwrap();
-            println!("{} Acquired R -> {}", self.id, right_fork);
+            let left_fork = match self.left.lock() {
+                Ok(fork) => fork,
+                Err(_) => return,
+            };
+            println!("{} Acquired {}", self.id, left_fork);
+
+            let right_fork = match self.right.lock() {
+                Ok(fork) => fork,
+                Err(_) => return,
+            };
+            println!("{} Acquired {}", self.id, right_fork);
 
             thread::sleep(Duration::new(0, 100000));
 
@@ -483,7 +1275,6 @@ mod dining_philisophers {
         }
     }
 
-    #[ignore = "Test is deadlock prone"]
     #[test]
     pub fn std() {
         for i in ITERATIONS {
@@ -517,4 +1308,282 @@ mod dining_philisophers {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod policy {
+    use super::{DFMutex, Policy};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    pub fn cycle_detection_rejects_circular_wait() {
+        let a = DFMutex::new(1);
+        let b = DFMutex::new(2);
+
+        let mut a1 = a.clone();
+        let mut b1 = b.clone();
+        let t1 = thread::spawn(move || {
+            let _guard = a1.lock().unwrap();
+            thread::sleep(Duration::from_millis(100));
+            b1.lock().map(|_| ())
+        });
+
+        thread::sleep(Duration::from_millis(50));
+
+        let mut b2 = b.clone();
+        let mut a2 = a.clone();
+        let _guard = b2.lock().unwrap();
+        let main_result = a2.lock().map(|_| ());
+
+        let t1_result = t1.join().unwrap();
+
+        // Exactly one side of the cycle is rejected; the other proceeds once
+        // the rejected side releases the lock it was already holding.
+        assert!(t1_result.is_err() ^ main_result.is_err());
+    }
+
+    #[test]
+    pub fn ordered_rejects_descending_acquire() {
+        let mut low = DFMutex::with_policy(1, Policy::Ordered);
+        let mut high = DFMutex::with_policy(2, Policy::Ordered);
+
+        assert!(low.id() < high.id());
+
+        {
+            let _low_guard = low.lock().unwrap();
+            assert!(high.lock().is_ok());
+        }
+
+        {
+            let _high_guard = high.lock().unwrap();
+            assert!(low.lock().is_err());
+        }
+    }
+}
+
+#[cfg(test)]
+mod try_lock {
+    use std::sync::TryLockError;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::DFMutex;
+
+    #[test]
+    pub fn succeeds_when_uncontended() {
+        let mut m = DFMutex::new(String::from("Lorem Ipsum"));
+
+        let data = m.try_lock().unwrap();
+        println!("{}", data);
+    }
+
+    #[test]
+    pub fn would_block_when_held() {
+        let m = DFMutex::new(String::from("Lorem Ipsum"));
+
+        let mut m1 = m.clone();
+        let _guard = m1.lock().unwrap();
+
+        let mut m2 = m.clone();
+        match m2.try_lock() {
+            Err(TryLockError::WouldBlock) => {}
+            other => panic!("expected WouldBlock, got {:?}", other.map(|_| ())),
+        };
+    }
+
+    #[test]
+    pub fn does_not_cut_in_front_of_a_queued_waiter() {
+        let m = DFMutex::new(0);
+
+        let mut holder = m.clone();
+        let guard = holder.lock().unwrap();
+
+        let mut waiter = m.clone();
+        let handle = thread::spawn(move || {
+            let _guard = waiter.lock().unwrap();
+        });
+
+        while m.waiters() == 0 {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        // The lock is free as far as `std::sync::Mutex` is concerned once
+        // `guard` drops, but a thread is already queued up for it, so a
+        // spinning `try_lock` must not be able to steal it.
+        drop(guard);
+
+        let mut spinner = m.clone();
+        match spinner.try_lock() {
+            Err(TryLockError::WouldBlock) => {}
+            other => panic!("expected WouldBlock, got {:?}", other.map(|_| ())),
+        };
+
+        handle.join().unwrap();
+    }
+
+    #[test]
+    pub fn respects_ordered_policy() {
+        use super::Policy;
+
+        let mut low = DFMutex::with_policy(1, Policy::Ordered);
+        let mut high = DFMutex::with_policy(2, Policy::Ordered);
+
+        assert!(low.id() < high.id());
+
+        let _high_guard = high.lock().unwrap();
+        match low.try_lock() {
+            Err(TryLockError::WouldBlock) => {}
+            other => panic!("expected WouldBlock, got {:?}", other.map(|_| ())),
+        };
+    }
+}
+
+#[cfg(test)]
+mod ownership {
+    use super::{DFMutex, spawn};
+
+    #[test]
+    pub fn into_inner_after_threads_join() {
+        let m = DFMutex::new(String::from("Lorem Ipsum"));
+
+        let closure = |mut dfm: DFMutex<String>| {
+            let _data = dfm.lock().unwrap();
+        };
+
+        let mut handles = Vec::new();
+        for _ in 0..8 {
+            handles.push(spawn(&m, closure));
+        }
+        for handle in handles.into_iter() {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(m.into_inner().unwrap(), "Lorem Ipsum");
+    }
+
+    #[test]
+    pub fn into_inner_fails_while_shared() {
+        let m = DFMutex::new(String::from("Lorem Ipsum"));
+        let _clone = m.clone();
+
+        assert!(m.into_inner().is_err());
+    }
+
+    #[test]
+    pub fn get_mut_gives_unsynchronized_access() {
+        let mut m = DFMutex::new(String::from("Lorem Ipsum"));
+
+        *m.get_mut().unwrap() = String::from("Dolor Sit Amet");
+
+        assert_eq!(m.into_inner().unwrap(), "Dolor Sit Amet");
+    }
+}
+
+#[cfg(test)]
+mod condvar {
+    use std::collections::VecDeque;
+    use std::thread;
+
+    use super::{DFCondvar, DFMutex};
+
+    const ITEMS: i32 = 50;
+
+    #[test]
+    pub fn producer_consumer_without_spurious_deadlock() {
+        let queue: DFMutex<VecDeque<i32>> = DFMutex::new(VecDeque::new());
+        let not_empty = DFCondvar::new();
+
+        let mut producer_queue = queue.clone();
+        let producer_cv = not_empty.clone();
+        let producer = thread::spawn(move || {
+            for i in 0..ITEMS {
+                let mut guard = producer_queue.lock().unwrap();
+                guard.push_back(i);
+                drop(guard);
+                producer_cv.notify_one();
+            }
+        });
+
+        let mut consumer_queue = queue.clone();
+        let consumer_cv = not_empty.clone();
+        let consumer = thread::spawn(move || {
+            let mut received = 0;
+            while received < ITEMS {
+                let mut guard = consumer_queue.lock().unwrap();
+                while guard.is_empty() {
+                    guard = consumer_cv.wait(guard);
+                }
+                let item = guard.pop_front().unwrap();
+                drop(guard);
+
+                println!("received {}", item);
+                received += 1;
+            }
+        });
+
+        producer.join().unwrap();
+        consumer.join().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod waiter_queue {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::DFMutex;
+
+    #[test]
+    pub fn fifo_order_under_contention() {
+        let m = DFMutex::new(Vec::new());
+
+        let mut holder = m.clone();
+        let guard = holder.lock().unwrap();
+
+        let mut handles = Vec::new();
+        for i in 0..8 {
+            let mut dfm = m.clone();
+            handles.push(thread::spawn(move || {
+                let mut data = dfm.lock().unwrap();
+                data.push(i);
+            }));
+            // Give each thread time to join the waiter queue before the next
+            // one spawns, so the queue order matches the spawn order.
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        drop(guard);
+
+        for handle in handles.into_iter() {
+            handle.join().unwrap();
+        }
+        drop(holder);
+
+        let order = m.into_inner().unwrap();
+        assert_eq!(order, (0..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    pub fn waiters_reflects_parked_threads() {
+        let m = DFMutex::new(0);
+
+        let mut holder = m.clone();
+        let guard = holder.lock().unwrap();
+
+        let mut dfm = m.clone();
+        let handle = thread::spawn(move || {
+            let _data = dfm.lock().unwrap();
+        });
+
+        while m.waiters() == 0 {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(m.waiters(), 1);
+
+        drop(guard);
+        handle.join().unwrap();
+
+        assert_eq!(m.waiters(), 0);
+    }
+}